@@ -1,17 +1,87 @@
 use base64;
 use blake2;
+use bs58;
+use chacha20poly1305::{
+    aead::{generic_array::GenericArray, Aead, NewAead, Payload},
+    XChaCha20Poly1305,
+};
 use ed25519_dalek::{self, SecretKey, PublicKey};
-use rand::OsRng;
+use failure::bail;
+use hmac::{Hmac, Mac};
+use rand::{OsRng, Rng};
+use sha2::Sha512;
+use zeroize::{Zeroize, Zeroizing};
 use std::{
-    fmt
+    fmt, fs,
+    io::Write,
+    os::unix::fs::OpenOptionsExt,
+    path::Path,
 };
 use crev_common::{self, serde::{as_base64, from_base64}};
+use proof::Proof;
 use Result;
 
-#[derive(Clone, Debug, Serialize, Deserialize)]
+/// Distinguishes the cryptographic suite (signature scheme + key/hash
+/// derivation) a [`PubId`] was generated under, so the proof format can
+/// evolve without breaking proofs signed under an older suite.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum IdType {
-#[serde(rename = "crev")]
-    Crev
+    /// ed25519 signatures with blake2b-derived keys; the original crev suite
+    #[serde(rename = "crev")]
+    Crev,
+    /// Any suite this version of crev does not know how to handle. Proofs
+    /// declaring it fail closed rather than silently verifying under the
+    /// default suite.
+    #[serde(other)]
+    Unknown,
+}
+
+impl IdType {
+    /// The prefix a [`PubId::keyid`] is tagged with, identifying which
+    /// suite produced it.
+    fn keyid_prefix(&self) -> &'static str {
+        match self {
+            IdType::Crev => "crev",
+            IdType::Unknown => "unknown",
+        }
+    }
+
+    /// Verifies `signature` over `msg` under this suite, dispatching to
+    /// the matching [`CryptoSuite`] impl.
+    pub fn verify_signature(&self, pubkey: &PublicKey, msg: &[u8], signature: &[u8]) -> Result<()> {
+        match self {
+            IdType::Crev => CrevV1Ed25519Blake2b::verify_signature(pubkey, msg, signature),
+            IdType::Unknown => bail!("cannot verify a signature under an unknown crypto suite"),
+        }
+    }
+}
+
+/// A pluggable signature/key-derivation suite. Selecting the suite from a
+/// proof's declared [`IdType`] is what lets a single crev store hold
+/// proofs signed under different algorithms as the format evolves.
+trait CryptoSuite {
+    fn derive_public_key(secret: &SecretKey) -> PublicKey;
+    fn sign(keypair: &ed25519_dalek::Keypair, msg: &[u8]) -> Vec<u8>;
+    fn verify_signature(pubkey: &PublicKey, msg: &[u8], signature: &[u8]) -> Result<()>;
+}
+
+/// The original crev suite: ed25519 signatures with blake2b-derived keys.
+struct CrevV1Ed25519Blake2b;
+
+impl CryptoSuite for CrevV1Ed25519Blake2b {
+    fn derive_public_key(secret: &SecretKey) -> PublicKey {
+        PublicKey::from_secret::<blake2::Blake2b>(secret)
+    }
+
+    fn sign(keypair: &ed25519_dalek::Keypair, msg: &[u8]) -> Vec<u8> {
+        keypair.sign::<blake2::Blake2b>(msg).to_bytes().to_vec()
+    }
+
+    fn verify_signature(pubkey: &PublicKey, msg: &[u8], signature: &[u8]) -> Result<()> {
+        let signature = ed25519_dalek::Signature::from_bytes(signature)?;
+        pubkey.verify::<blake2::Blake2b>(msg, &signature)?;
+        Ok(())
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -36,6 +106,37 @@ impl PubId {
             type_: IdType::Crev,
         }
     }
+
+    /// A suite-tagged identifier for this key, used as the signer field of
+    /// a proof signature so verification can select the right
+    /// [`CryptoSuite`] without consulting anything but the proof itself.
+    pub fn keyid(&self) -> String {
+        format!(
+            "{}:{}",
+            self.type_.keyid_prefix(),
+            base64::encode_config(&self.id, base64::URL_SAFE)
+        )
+    }
+
+    /// Parses a `keyid` produced by [`PubId::keyid`] back into its
+    /// declared suite and raw public-key bytes. Fails closed on suite
+    /// prefixes this version of crev does not recognize.
+    pub fn parse_keyid(keyid: &str) -> Result<(IdType, Vec<u8>)> {
+        let sep = keyid
+            .find(':')
+            .ok_or_else(|| failure::err_msg("keyid is missing a crypto-suite prefix"))?;
+        let (prefix, encoded) = keyid.split_at(sep);
+        let encoded = &encoded[1..];
+
+        let type_ = match prefix {
+            "crev" => IdType::Crev,
+            other => bail!("unknown crypto suite '{}'", other),
+        };
+        let id = base64::decode_config(encoded, base64::URL_SAFE)
+            .map_err(|e| failure::err_msg(e.to_string()))?;
+
+        Ok((type_, id))
+    }
 }
 
 impl fmt::Display for PubId {
@@ -50,11 +151,62 @@ pub struct OwnId {
     pub keypair: ed25519_dalek::Keypair,
 }
 
+/// Derivation path used when no explicit path is given, matching
+/// the `m/44'/0'/0'/0'` convention from the BIP43/SLIP10 proposals.
+const DEFAULT_DERIVATION_PATH: [u32; 4] = [44, 0, 0, 0];
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// SLIP-0010 master key and chain code for the `ed25519` curve.
+fn slip10_master_key(seed: &[u8]) -> ([u8; 32], [u8; 32]) {
+    let mut mac = HmacSha512::new_varkey(b"ed25519 seed").expect("HMAC accepts a key of any size");
+    mac.input(seed);
+    let result = mac.result().code();
+
+    let mut key = [0u8; 32];
+    let mut chain_code = [0u8; 32];
+    key.copy_from_slice(&result[..32]);
+    chain_code.copy_from_slice(&result[32..]);
+    (key, chain_code)
+}
+
+/// SLIP-0010 hardened child derivation for the `ed25519` curve.
+fn slip10_derive_child(key: &[u8; 32], chain_code: &[u8; 32], index: u32) -> ([u8; 32], [u8; 32]) {
+    let hardened_index = index | 0x8000_0000;
+
+    let mut data = Vec::with_capacity(1 + 32 + 4);
+    data.push(0x00);
+    data.extend_from_slice(key);
+    data.extend_from_slice(&hardened_index.to_be_bytes());
+
+    let mut mac = HmacSha512::new_varkey(chain_code).expect("HMAC accepts a key of any size");
+    mac.input(&data);
+    let result = mac.result().code();
+
+    let mut child_key = [0u8; 32];
+    let mut child_chain_code = [0u8; 32];
+    child_key.copy_from_slice(&result[..32]);
+    child_chain_code.copy_from_slice(&result[32..]);
+    (child_key, child_chain_code)
+}
+
+/// Walks a hardened-only SLIP-0010 derivation path, returning the final
+/// 32-byte `ed25519` secret key.
+fn slip10_derive_path(seed: &[u8], path: &[u32]) -> [u8; 32] {
+    let (mut key, mut chain_code) = slip10_master_key(seed);
+    for index in path {
+        let (child_key, child_chain_code) = slip10_derive_child(&key, &chain_code, *index);
+        key = child_key;
+        chain_code = child_chain_code;
+    }
+    key
+}
+
 impl OwnId {
     pub fn new(url: String, sec_key: Vec<u8>) -> Result<Self> {
 
         let sec_key = SecretKey::from_bytes(&sec_key)?;
-        let calculated_pub_key: PublicKey = PublicKey::from_secret::<blake2::Blake2b>(&sec_key);
+        let calculated_pub_key: PublicKey = CrevV1Ed25519Blake2b::derive_public_key(&sec_key);
 
         Ok(Self {
 
@@ -67,7 +219,7 @@ impl OwnId {
     }
 
     pub fn sign(&self, msg: &[u8]) -> Vec<u8> {
-        self.keypair.sign::<blake2::Blake2b>(&msg).to_bytes().to_vec()
+        CrevV1Ed25519Blake2b::sign(&self.keypair, msg)
     }
 
     pub fn type_as_string(&self) -> String {
@@ -87,4 +239,319 @@ impl OwnId {
             keypair,
         }
     }
-}
\ No newline at end of file
+
+    /// Generates a fresh identity together with the BIP39 mnemonic it was
+    /// derived from, so the mnemonic can be written down and used later to
+    /// recover the exact same identity with [`OwnId::from_mnemonic`].
+    ///
+    /// Uses 256 bits of entropy (a 24-word mnemonic) and an empty BIP39
+    /// passphrase.
+    pub fn generate_with_mnemonic(url: String) -> (Self, String) {
+        let mut csprng: OsRng = OsRng::new().unwrap();
+        let mut entropy = [0u8; 32];
+        csprng.fill_bytes(&mut entropy);
+
+        let mnemonic = bip39::Mnemonic::from_entropy(&entropy, bip39::Language::English)
+            .expect("locally generated entropy is always valid");
+        let phrase = mnemonic.into_phrase();
+
+        let id = Self::from_mnemonic(url, &phrase, "")
+            .expect("a mnemonic we just generated ourselves is always valid");
+
+        (id, phrase)
+    }
+
+    /// Reconstructs an identity from a BIP39 mnemonic and an optional
+    /// passphrase, using SLIP-0010 `ed25519` derivation along
+    /// `m/44'/0'/0'/0'`.
+    ///
+    /// This is the inverse of [`OwnId::generate_with_mnemonic`].
+    pub fn from_mnemonic(url: String, phrase: &str, passphrase: &str) -> Result<Self> {
+        let mnemonic = bip39::Mnemonic::from_phrase(phrase, bip39::Language::English)
+            .map_err(|e| failure::err_msg(e.to_string()))?;
+        let seed = bip39::Seed::new(&mnemonic, passphrase);
+
+        let sec_key = slip10_derive_path(seed.as_bytes(), &DEFAULT_DERIVATION_PATH);
+
+        Self::new(url, sec_key.to_vec())
+    }
+
+    /// Serializes the keypair as a raw 64-byte `secret || public` buffer,
+    /// matching the layout used by most `ed25519` identity tooling.
+    pub fn to_bytes(&self) -> [u8; 64] {
+        let mut bytes = [0u8; 64];
+        bytes[..32].copy_from_slice(self.keypair.secret.as_bytes());
+        bytes[32..].copy_from_slice(self.keypair.public.as_bytes());
+        bytes
+    }
+
+    /// Reconstructs an identity from the raw `secret || public` layout
+    /// produced by [`OwnId::to_bytes`].
+    pub fn from_bytes(url: &str, bytes: &[u8]) -> Result<Self> {
+        if bytes.len() != 64 {
+            bail!(
+                "expected a 64-byte secret||public ed25519 keypair, got {} bytes",
+                bytes.len()
+            );
+        }
+
+        let id = Self::new(url.to_string(), bytes[..32].to_vec())?;
+        if id.keypair.public.as_bytes() != &bytes[32..64] {
+            bail!("public key does not match the provided secret key");
+        }
+
+        Ok(id)
+    }
+
+    pub fn to_base58_string(&self) -> String {
+        bs58::encode(self.to_bytes().to_vec()).into_string()
+    }
+
+    pub fn from_base58_string(url: &str, s: &str) -> Result<Self> {
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map_err(|e| failure::err_msg(e.to_string()))?;
+        Self::from_bytes(url, &bytes)
+    }
+
+    /// Writes the raw keypair to `path`, creating it with `0600`
+    /// permissions from the start so the secret key is never briefly left
+    /// at the process's default umask (typically world- or
+    /// group-readable) before being locked down.
+    pub fn write_to_file(&self, path: &Path) -> Result<()> {
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(path)?;
+        file.write_all(&self.to_bytes())?;
+
+        Ok(())
+    }
+
+    /// Loads a keypair previously written with [`OwnId::write_to_file`].
+    pub fn read_from_file(url: &str, path: &Path) -> Result<Self> {
+        let bytes = fs::read(path)?;
+        Self::from_bytes(url, &bytes)
+    }
+
+    /// Adds this identity's signature to an already-signed `proof`,
+    /// without altering its body, so multiple reviewers can attest to the
+    /// same review content (e.g. pair review, audit sign-off).
+    pub fn countersign(&self, existing: &Proof) -> Proof {
+        let mut proof = existing.clone();
+        let signature = self.sign(existing.body.as_bytes());
+        proof.signatures.push((
+            self.id.keyid(),
+            base64::encode_config(&signature, base64::URL_SAFE),
+        ));
+        proof
+    }
+
+    /// Encrypts this identity's secret key under `passphrase`, producing a
+    /// [`LockedId`] that is safe to commit to a repo or sync alongside the
+    /// existing plaintext `PubId` display format.
+    pub fn to_locked(&self, passphrase: &str) -> Result<LockedId> {
+        let mut csprng: OsRng = OsRng::new().unwrap();
+
+        let mut salt = [0u8; SALT_LENGTH];
+        csprng.fill_bytes(&mut salt);
+        let mut nonce = [0u8; NONCE_LENGTH];
+        csprng.fill_bytes(&mut nonce);
+
+        let aad = locked_id_aad(&self.id.url, &self.id.type_, &self.id.id);
+        let mut key = derive_key_from_passphrase(passphrase, &salt)?;
+        let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(&key));
+        let ciphertext = cipher
+            .encrypt(
+                GenericArray::from_slice(&nonce),
+                Payload {
+                    msg: self.keypair.secret.as_bytes().as_ref(),
+                    aad: &aad,
+                },
+            )
+            .map_err(|_| failure::err_msg("encryption of the secret key failed"))?;
+        key.zeroize();
+
+        Ok(LockedId {
+            url: self.id.url.clone(),
+            type_: self.id.type_.clone(),
+            pubkey: self.id.id.clone(),
+            salt: salt.to_vec(),
+            nonce: nonce.to_vec(),
+            ciphertext,
+        })
+    }
+}
+
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+
+/// Derives a 32-byte symmetric key from a user passphrase and a random
+/// salt using Argon2, so a weak or short passphrase is still expensive to
+/// brute-force.
+fn derive_key_from_passphrase(passphrase: &str, salt: &[u8]) -> Result<[u8; 32]> {
+    let config = argon2::Config::default();
+    let mut hash = argon2::hash_raw(passphrase.as_bytes(), salt, &config)
+        .map_err(|e| failure::err_msg(e.to_string()))?;
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&hash[..32]);
+    hash.zeroize();
+    Ok(key)
+}
+
+/// Builds the AEAD associated data that binds a [`LockedId`]'s plaintext
+/// metadata (`url`, `type`, `pubkey`) to its ciphertext, so tampering with
+/// any of that metadata in the stored container invalidates the auth tag
+/// instead of silently being accepted by `unlock`.
+fn locked_id_aad(url: &str, type_: &IdType, pubkey: &[u8]) -> Vec<u8> {
+    let mut aad = Vec::with_capacity(url.len() + pubkey.len() + 16);
+    aad.extend_from_slice(&(url.len() as u64).to_le_bytes());
+    aad.extend_from_slice(url.as_bytes());
+    aad.extend_from_slice(type_.keyid_prefix().as_bytes());
+    aad.extend_from_slice(&(pubkey.len() as u64).to_le_bytes());
+    aad.extend_from_slice(pubkey);
+    aad
+}
+
+/// A passphrase-encrypted [`OwnId`]: the secret key is sealed with
+/// XChaCha20-Poly1305 under a key derived from the passphrase via Argon2,
+/// so the container can be committed to a repo or synced without exposing
+/// the signing key.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LockedId {
+    pub url: String,
+    #[serde(rename = "type")]
+    pub type_: IdType,
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    pub pubkey: Vec<u8>,
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    salt: Vec<u8>,
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    nonce: Vec<u8>,
+    #[serde(serialize_with = "as_base64", deserialize_with = "from_base64")]
+    ciphertext: Vec<u8>,
+}
+
+impl LockedId {
+    /// Decrypts the secret key with `passphrase`, reconstructing the
+    /// original [`OwnId`]. Fails if the passphrase is wrong or the
+    /// container has been tampered with.
+    pub fn unlock(&self, passphrase: &str) -> Result<OwnId> {
+        if self.nonce.len() != NONCE_LENGTH {
+            bail!(
+                "expected a {}-byte nonce, got {} bytes",
+                NONCE_LENGTH,
+                self.nonce.len()
+            );
+        }
+
+        let aad = locked_id_aad(&self.url, &self.type_, &self.pubkey);
+        let mut key = derive_key_from_passphrase(passphrase, &self.salt)?;
+        let cipher = XChaCha20Poly1305::new(GenericArray::from_slice(&key));
+        // Wrapped in `Zeroizing` so the decrypted secret-key bytes are wiped
+        // on drop even though `OwnId::new` needs its own owned copy below.
+        let sec_key = Zeroizing::new(
+            cipher
+                .decrypt(
+                    GenericArray::from_slice(&self.nonce),
+                    Payload {
+                        msg: self.ciphertext.as_ref(),
+                        aad: &aad,
+                    },
+                )
+                .map_err(|_| failure::err_msg("wrong passphrase or corrupted locked id"))?,
+        );
+        key.zeroize();
+
+        let id = OwnId::new(self.url.clone(), sec_key.to_vec())?;
+
+        // Belt-and-suspenders on top of the AAD binding above: refuse to
+        // hand back an identity whose public key doesn't match what this
+        // container claims to hold.
+        if id.id.id != self.pubkey {
+            bail!("locked id's stored public key does not match its decrypted secret key");
+        }
+
+        Ok(id)
+    }
+}
+
+impl fmt::Display for LockedId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        crev_common::serde::write_as_headerless_yaml(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mnemonic_round_trip_recovers_identity() {
+        let (id, phrase) = OwnId::generate_with_mnemonic("https://example.com/alice".into());
+        let recovered = OwnId::from_mnemonic("https://example.com/alice".into(), &phrase, "")
+            .expect("a mnemonic we just generated must recover the same identity");
+
+        assert_eq!(id.to_bytes(), recovered.to_bytes());
+    }
+
+    #[test]
+    fn mnemonic_recovery_is_sensitive_to_passphrase() {
+        let (id, phrase) = OwnId::generate_with_mnemonic("https://example.com/alice".into());
+        let recovered =
+            OwnId::from_mnemonic("https://example.com/alice".into(), &phrase, "extra")
+                .expect("a non-empty BIP39 passphrase is still a valid one");
+
+        assert_ne!(id.to_bytes(), recovered.to_bytes());
+    }
+
+    #[test]
+    fn locked_id_round_trips_with_correct_passphrase() {
+        let id = OwnId::generate("https://example.com/alice".into());
+        let locked = id
+            .to_locked("correct horse battery staple")
+            .expect("encrypting the secret key must succeed");
+        let unlocked = locked
+            .unlock("correct horse battery staple")
+            .expect("decrypting with the right passphrase must succeed");
+
+        assert_eq!(id.to_bytes(), unlocked.to_bytes());
+    }
+
+    #[test]
+    fn locked_id_rejects_wrong_passphrase() {
+        let id = OwnId::generate("https://example.com/alice".into());
+        let locked = id
+            .to_locked("correct horse battery staple")
+            .expect("encrypting the secret key must succeed");
+
+        assert!(locked.unlock("wrong passphrase").is_err());
+    }
+
+    #[test]
+    fn locked_id_with_malformed_nonce_errors_instead_of_panicking() {
+        let id = OwnId::generate("https://example.com/alice".into());
+        let mut locked = id
+            .to_locked("correct horse battery staple")
+            .expect("encrypting the secret key must succeed");
+        locked.nonce.pop();
+
+        assert!(locked.unlock("correct horse battery staple").is_err());
+    }
+
+    #[test]
+    fn locked_id_rejects_tampered_metadata() {
+        let id = OwnId::generate("https://example.com/alice".into());
+        let mut locked = id
+            .to_locked("correct horse battery staple")
+            .expect("encrypting the secret key must succeed");
+        locked.url = "https://example.com/mallory".into();
+
+        assert!(locked
+            .unlock("correct horse battery staple")
+            .is_err());
+    }
+}
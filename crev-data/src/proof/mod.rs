@@ -1,7 +1,10 @@
 //! Some common stuff for both Review and Trust Proofs
 
+use base64;
 use chrono::{self, prelude::*};
 use crev_common;
+use crate::id::PubId;
+use ed25519_dalek::PublicKey;
 use failure::bail;
 use std::{
     default, fmt, fs,
@@ -57,6 +60,11 @@ impl ProofType {
     }
 }
 
+/// A single signature attached to a proof, paired with the `keyid` (the
+/// signer's suite-tagged public key, as produced by [`PubId::keyid`]) that
+/// produced it.
+pub type SignatureEntry = (String, String);
+
 /// Serialized Proof
 ///
 /// A signed proof containing some signed `Content`
@@ -64,8 +72,8 @@ impl ProofType {
 pub(crate) struct Serialized {
     /// Serialized content
     pub body: String,
-    /// Signature over the body
-    pub signature: String,
+    /// One or more signatures over the body, e.g. from co-signing reviewers
+    pub signatures: Vec<SignatureEntry>,
     /// Type of the `body` (`Content`)
     pub type_: ProofType,
 }
@@ -74,20 +82,33 @@ pub(crate) struct Serialized {
 /// A `Proof` with it's content parsed and ready.
 pub struct Proof {
     pub body: String,
-    pub signature: String,
+    pub signatures: Vec<SignatureEntry>,
     pub digest: Vec<u8>,
     pub content: Content,
 }
 
+fn write_signatures(
+    f: &mut fmt::Formatter<'_>,
+    type_: ProofType,
+    signatures: &[SignatureEntry],
+) -> fmt::Result {
+    for (keyid, signature) in signatures {
+        f.write_str(type_.begin_signature())?;
+        f.write_str("\n")?;
+        f.write_str(keyid)?;
+        f.write_str("\n")?;
+        f.write_str(signature)?;
+        f.write_str("\n")?;
+    }
+    Ok(())
+}
+
 impl fmt::Display for Serialized {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(self.type_.begin_block())?;
         f.write_str("\n")?;
         f.write_str(&self.body)?;
-        f.write_str(self.type_.begin_signature())?;
-        f.write_str("\n")?;
-        f.write_str(&self.signature)?;
-        f.write_str("\n")?;
+        write_signatures(f, self.type_, &self.signatures)?;
         f.write_str(self.type_.end_block())?;
         f.write_str("\n")?;
 
@@ -97,135 +118,209 @@ impl fmt::Display for Serialized {
 
 impl fmt::Display for Proof {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(self.content.proof_type().begin_block())?;
+        let type_ = self.content.proof_type();
+        f.write_str(type_.begin_block())?;
         f.write_str("\n")?;
         f.write_str(&self.body)?;
-        f.write_str(self.content.proof_type().begin_signature())?;
-        f.write_str("\n")?;
-        f.write_str(&self.signature)?;
-        f.write_str("\n")?;
-        f.write_str(self.content.proof_type().end_block())?;
+        write_signatures(f, type_, &self.signatures)?;
+        f.write_str(type_.end_block())?;
         f.write_str("\n")?;
 
         Ok(())
     }
 }
 
-impl Serialized {
-    pub fn to_parsed(&self) -> Result<Proof> {
-        Ok(Proof {
-            body: self.body.clone(),
-            signature: self.signature.clone(),
-            digest: crev_common::blake2b256sum(&self.body.as_bytes()),
-            content: match self.type_ {
-                ProofType::Code => review::Code::parse(&self.body)?.into(),
-                ProofType::Package => review::Package::parse(&self.body)?.into(),
-                ProofType::Trust => Trust::parse(&self.body)?.into(),
-            },
-        })
-    }
+#[derive(PartialEq, Eq)]
+enum ParseStage {
+    None,
+    Body,
+    // expecting the keyid line that identifies the next co-signer
+    SignatureKeyid,
+    SignatureBody,
+}
 
-    pub fn parse(reader: impl io::Read) -> Result<Vec<Self>> {
-        let reader = std::io::BufReader::new(reader);
+impl Default for ParseStage {
+    fn default() -> Self {
+        ParseStage::None
+    }
+}
 
-        #[derive(PartialEq, Eq)]
-        enum Stage {
-            None,
-            Body,
-            Signature,
-        }
+/// The block-delimited state machine behind [`Serialized::parse`] and
+/// [`Serialized::parse_iter`]. Line-oriented so it can be fed one line at
+/// a time by either a synchronous or an async reader.
+struct ParseState {
+    stage: ParseStage,
+    body: String,
+    keyid: String,
+    signature: String,
+    signatures: Vec<SignatureEntry>,
+    type_: ProofType,
+}
 
-        impl Default for Stage {
-            fn default() -> Self {
-                Stage::None
-            }
+impl default::Default for ParseState {
+    fn default() -> Self {
+        ParseState {
+            stage: Default::default(),
+            body: Default::default(),
+            keyid: Default::default(),
+            signature: Default::default(),
+            signatures: vec![],
+            type_: ProofType::Trust, // whatever
         }
+    }
+}
 
-        struct State {
-            stage: Stage,
-            body: String,
-            signature: String,
-            type_: ProofType,
-            proofs: Vec<Serialized>,
+impl ParseState {
+    /// Finishes the signature block currently being accumulated, if any,
+    /// moving it into `signatures`.
+    fn finish_signature(&mut self) {
+        if !self.keyid.is_empty() || !self.signature.is_empty() {
+            let signature = mem::replace(&mut self.signature, String::new());
+            self.signatures.push((
+                mem::replace(&mut self.keyid, String::new()),
+                signature.trim_end().to_string(),
+            ));
         }
+    }
 
-        impl default::Default for State {
-            fn default() -> Self {
-                State {
-                    stage: Default::default(),
-                    body: Default::default(),
-                    signature: Default::default(),
-                    type_: ProofType::Trust, // whatever
-                    proofs: vec![],
+    /// Feeds a single line into the state machine, returning a completed
+    /// `Serialized` once its `end_block` has been seen.
+    fn process_line(&mut self, line: &str) -> Result<Option<Serialized>> {
+        match self.stage {
+            ParseStage::None => {
+                let line = line.trim();
+                if line.is_empty() {
+                } else if line == ProofType::Code.begin_block() {
+                    self.type_ = ProofType::Code;
+                    self.stage = ParseStage::Body;
+                } else if line == ProofType::Trust.begin_block() {
+                    self.type_ = ProofType::Trust;
+                    self.stage = ParseStage::Body;
+                } else if line == ProofType::Package.begin_block() {
+                    self.type_ = ProofType::Package;
+                    self.stage = ParseStage::Body;
+                } else {
+                    bail!("Parsing error when looking for start of code review proof");
+                }
+            }
+            ParseStage::Body => {
+                if line.trim() == self.type_.begin_signature() {
+                    self.stage = ParseStage::SignatureKeyid;
+                } else {
+                    self.body += line;
+                    self.body += "\n";
+                }
+                if self.body.len() > MAX_PROOF_BODY_LENGTH {
+                    bail!("Proof body too long");
+                }
+            }
+            ParseStage::SignatureKeyid => {
+                self.keyid = line.trim().to_string();
+                self.stage = ParseStage::SignatureBody;
+            }
+            ParseStage::SignatureBody => {
+                if line.trim() == self.type_.begin_signature() {
+                    // another co-signature follows
+                    self.finish_signature();
+                    self.stage = ParseStage::SignatureKeyid;
+                } else if line.trim() == self.type_.end_block() {
+                    self.finish_signature();
+                    self.stage = ParseStage::None;
+                    return Ok(Some(Serialized {
+                        body: mem::replace(&mut self.body, String::new()),
+                        signatures: mem::replace(&mut self.signatures, vec![]),
+                        type_: self.type_,
+                    }));
+                } else {
+                    self.signature += line;
+                    self.signature += "\n";
+                }
+                if self.signature.len() > 2000 {
+                    bail!("Signature too long");
                 }
             }
         }
+        Ok(None)
+    }
 
-        impl State {
-            fn process_line(&mut self, line: &str) -> Result<()> {
-                match self.stage {
-                    Stage::None => {
-                        let line = line.trim();
-                        if line.is_empty() {
-                        } else if line == ProofType::Code.begin_block() {
-                            self.type_ = ProofType::Code;
-                            self.stage = Stage::Body;
-                        } else if line == ProofType::Trust.begin_block() {
-                            self.type_ = ProofType::Trust;
-                            self.stage = Stage::Body;
-                        } else if line == ProofType::Package.begin_block() {
-                            self.type_ = ProofType::Package;
-                            self.stage = Stage::Body;
-                        } else {
-                            bail!("Parsing error when looking for start of code review proof");
-                        }
-                    }
-                    Stage::Body => {
-                        if line.trim() == self.type_.begin_signature() {
-                            self.stage = Stage::Signature;
-                        } else {
-                            self.body += line;
-                            self.body += "\n";
-                        }
-                        if self.body.len() > MAX_PROOF_BODY_LENGTH {
-                            bail!("Proof body too long");
-                        }
-                    }
-                    Stage::Signature => {
-                        if line.trim() == self.type_.end_block() {
-                            self.stage = Stage::None;
-                            self.proofs.push(Serialized {
-                                body: mem::replace(&mut self.body, String::new()),
-                                signature: mem::replace(&mut self.signature, String::new()),
-                                type_: self.type_,
-                            });
-                        } else {
-                            self.signature += line;
-                            self.signature += "\n";
-                        }
-                        if self.signature.len() > 2000 {
-                            bail!("Signature too long");
-                        }
+    fn finish(&self) -> Result<()> {
+        if self.stage != ParseStage::None {
+            bail!("Unexpected EOF while parsing");
+        }
+        Ok(())
+    }
+}
+
+/// Iterator returned by [`Serialized::parse_iter`], yielding one `Serialized`
+/// at a time as soon as its `begin_block`/`end_block` span completes,
+/// instead of buffering the whole reader into memory up front.
+pub struct SerializedIter<R> {
+    lines: io::Lines<io::BufReader<R>>,
+    state: ParseState,
+    done: bool,
+}
+
+impl<R: io::Read> Iterator for SerializedIter<R> {
+    type Item = Result<Serialized>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        loop {
+            match self.lines.next() {
+                Some(Ok(line)) => match self.state.process_line(&line) {
+                    Ok(Some(serialized)) => return Some(Ok(serialized)),
+                    Ok(None) => continue,
+                    Err(e) => {
+                        self.done = true;
+                        return Some(Err(e));
                     }
+                },
+                Some(Err(e)) => {
+                    self.done = true;
+                    return Some(Err(e.into()));
                 }
-                Ok(())
-            }
-
-            fn finish(self) -> Result<Vec<Serialized>> {
-                if self.stage != Stage::None {
-                    bail!("Unexpected EOF while parsing");
+                None => {
+                    self.done = true;
+                    return self.state.finish().err().map(Err);
                 }
-                Ok(self.proofs)
             }
         }
+    }
+}
+
+impl Serialized {
+    pub fn to_parsed(&self) -> Result<Proof> {
+        Ok(Proof {
+            body: self.body.clone(),
+            signatures: self.signatures.clone(),
+            digest: crev_common::blake2b256sum(&self.body.as_bytes()),
+            content: match self.type_ {
+                ProofType::Code => review::Code::parse(&self.body)?.into(),
+                ProofType::Package => review::Package::parse(&self.body)?.into(),
+                ProofType::Trust => Trust::parse(&self.body)?.into(),
+            },
+        })
+    }
 
-        let mut state: State = Default::default();
+    /// Parses every proof out of `reader` eagerly, buffering them all into
+    /// a `Vec`. Prefer [`Serialized::parse_iter`] for large proof
+    /// repositories where buffering everything up front is wasteful.
+    pub fn parse(reader: impl io::Read) -> Result<Vec<Self>> {
+        Self::parse_iter(reader).collect()
+    }
 
-        for line in reader.lines() {
-            state.process_line(&line?)?;
+    /// Lazily parses `reader`, yielding one proof at a time as soon as its
+    /// block is complete, without holding the rest of the stream in
+    /// memory. This allows incremental verification and early termination
+    /// while fetching proofs over the network.
+    pub fn parse_iter(reader: impl io::Read) -> SerializedIter<impl io::Read> {
+        SerializedIter {
+            lines: std::io::BufReader::new(reader).lines(),
+            state: ParseState::default(),
+            done: false,
         }
-
-        state.finish()
     }
 }
 
@@ -243,16 +338,51 @@ impl Proof {
         Ok(v)
     }
 
-    pub fn signature(&self) -> &str {
-        self.signature.trim()
+    /// Lazily parses `reader`, yielding one `Proof` at a time as its
+    /// underlying `Serialized` block completes. See
+    /// [`Serialized::parse_iter`].
+    pub fn parse_iter(reader: impl io::Read) -> impl Iterator<Item = Result<Self>> {
+        Serialized::parse_iter(reader).map(|serialized| serialized?.to_parsed())
     }
 
+    /// Verifies that every attached co-signature is a valid signature over
+    /// the proof's body, *and* that at least one of them was produced by
+    /// the key the body itself declares as its author — a self-declared
+    /// `keyid` on a signature block is not, by itself, proof of who wrote
+    /// the proof.
     pub fn verify(&self) -> Result<()> {
-        let pubkey = self.content.author_id();
-        pubkey.verify_signature(self.body.as_bytes(), self.signature())?;
+        verify_signatures(self.body.as_bytes(), &self.signatures, &self.content.author_id())
+    }
+}
 
-        Ok(())
+/// Checks every `(keyid, signature)` entry against `body`, and requires
+/// that at least one of them belongs to `author_id` — preventing a proof
+/// from verifying merely because *someone's* signature checks out,
+/// regardless of who the body claims wrote it.
+fn verify_signatures(body: &[u8], signatures: &[SignatureEntry], author_id: &PubId) -> Result<()> {
+    if signatures.is_empty() {
+        bail!("proof has no signatures");
     }
+
+    let mut author_signed = false;
+
+    for (keyid, signature) in signatures {
+        let (type_, pubkey_bytes) = PubId::parse_keyid(keyid.trim())?;
+        let pubkey = PublicKey::from_bytes(&pubkey_bytes)?;
+        let signature_bytes = base64::decode_config(signature.trim(), base64::URL_SAFE)
+            .map_err(|e| failure::err_msg(e.to_string()))?;
+        type_.verify_signature(&pubkey, body, &signature_bytes)?;
+
+        if pubkey_bytes == author_id.id {
+            author_signed = true;
+        }
+    }
+
+    if !author_signed {
+        bail!("proof's declared author did not sign it");
+    }
+
+    Ok(())
 }
 
 fn equals_default_digest_type(s: &str) -> bool {
@@ -274,3 +404,129 @@ pub fn default_revision_type() -> String {
 fn equals_default<T: Default + PartialEq>(t: &T) -> bool {
     *t == Default::default()
 }
+
+/// Async, streaming counterpart of [`Serialized::parse_iter`], for
+/// scanning large aggregated proof files over the network without
+/// blocking or buffering everything in memory.
+#[cfg(feature = "async")]
+pub mod r#async {
+    use super::{mem, ParseState, Serialized};
+    use crate::Result;
+    use async_std::io::BufRead as AsyncBufRead;
+    use async_std::prelude::*;
+    use futures::stream::{self, Stream};
+
+    /// Strips at most one trailing `\n`, and then at most one trailing
+    /// `\r`, matching the line-ending handling of `std::io::BufRead::lines`
+    /// so the same proof text parses identically whether it is fed through
+    /// [`super::Serialized::parse_iter`] or this async stream.
+    fn strip_line_ending(mut line: String) -> String {
+        if line.ends_with('\n') {
+            line.pop();
+            if line.ends_with('\r') {
+                line.pop();
+            }
+        }
+        line
+    }
+
+    /// Parses `reader` into a stream of `Serialized` proofs, yielding each
+    /// one as soon as its `begin_block`/`end_block` span completes.
+    pub fn parse_stream(
+        reader: impl AsyncBufRead + Unpin + Send + 'static,
+    ) -> impl Stream<Item = Result<Serialized>> {
+        stream::unfold(
+            (reader, ParseState::default(), false),
+            |(mut reader, mut state, done)| async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    let mut line = String::new();
+                    match reader.read_line(&mut line).await {
+                        Ok(0) => {
+                            return state
+                                .finish()
+                                .err()
+                                .map(|e| (Err(e), (reader, state, true)));
+                        }
+                        Ok(_) => {
+                            let line = strip_line_ending(mem::replace(&mut line, String::new()));
+                            match state.process_line(&line) {
+                                Ok(Some(serialized)) => {
+                                    return Some((Ok(serialized), (reader, state, false)))
+                                }
+                                Ok(None) => continue,
+                                Err(e) => return Some((Err(e), (reader, state, true))),
+                            }
+                        }
+                        Err(e) => return Some((Err(e.into()), (reader, state, true))),
+                    }
+                }
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::id::OwnId;
+
+    fn signature_entry(id: &OwnId, body: &[u8]) -> SignatureEntry {
+        (
+            id.id.keyid(),
+            base64::encode_config(&id.sign(body), base64::URL_SAFE),
+        )
+    }
+
+    #[test]
+    fn verify_signatures_requires_the_declared_author_to_have_signed() {
+        let author = OwnId::generate("https://example.com/author".into());
+        let impostor = OwnId::generate("https://example.com/impostor".into());
+        let body = b"some proof body\n";
+
+        let impostor_only = vec![signature_entry(&impostor, body)];
+        assert!(
+            verify_signatures(body, &impostor_only, &author.id).is_err(),
+            "an impostor's own valid signature must not satisfy an author check for someone else"
+        );
+
+        let with_author = vec![
+            signature_entry(&author, body),
+            signature_entry(&impostor, body),
+        ];
+        verify_signatures(body, &with_author, &author.id)
+            .expect("the author's own valid co-signature must satisfy verification");
+    }
+
+    #[test]
+    fn verify_signatures_rejects_empty_signature_list() {
+        let author = OwnId::generate("https://example.com/author".into());
+        assert!(verify_signatures(b"body", &[], &author.id).is_err());
+    }
+
+    #[test]
+    fn multi_signature_block_round_trips_through_serialized() {
+        let alice = OwnId::generate("https://example.com/alice".into());
+        let bob = OwnId::generate("https://example.com/bob".into());
+        let body = "key: value\n".to_string();
+
+        let serialized = Serialized {
+            signatures: vec![
+                signature_entry(&alice, body.as_bytes()),
+                signature_entry(&bob, body.as_bytes()),
+            ],
+            body: body.clone(),
+            type_: ProofType::Trust,
+        };
+
+        let text = serialized.to_string();
+        let mut parsed = Serialized::parse(text.as_bytes()).expect("the text we just wrote must parse");
+        assert_eq!(parsed.len(), 1);
+        let parsed = parsed.remove(0);
+
+        assert_eq!(parsed.body, serialized.body);
+        assert_eq!(parsed.signatures, serialized.signatures);
+    }
+}